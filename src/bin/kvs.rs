@@ -1,4 +1,5 @@
 use std::env;
+use std::path::PathBuf;
 
 use clap::{Parser, Subcommand};
 use kvs::error::Result;
@@ -21,6 +22,15 @@ enum Commands {
 
     #[command(name = "rm")]
     Remove { key: String },
+
+    #[command(name = "stats")]
+    Stats,
+
+    #[command(name = "export")]
+    Export { file: PathBuf },
+
+    #[command(name = "import")]
+    Import { file: PathBuf },
 }
 
 fn main() -> Result<()> {
@@ -44,5 +54,16 @@ fn main() -> Result<()> {
             }
             _ => Ok(()),
         },
+        Commands::Stats => {
+            let stats = kvstore.stats()?;
+            println!("live keys: {}", stats.live_keys);
+            println!("segments: {}", stats.segments);
+            println!("total bytes: {}", stats.total_bytes);
+            println!("live bytes: {}", stats.live_bytes);
+            println!("dead byte ratio: {:.4}", stats.dead_byte_ratio);
+            Ok(())
+        }
+        Commands::Export { file } => kvstore.export(&file),
+        Commands::Import { file } => kvstore.import(&file),
     }
 }
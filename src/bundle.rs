@@ -0,0 +1,139 @@
+use std::{
+    fs::File,
+    io::{Read, Seek, Write},
+    path::Path,
+};
+
+use crate::error::{KvsError, Result};
+
+const MAGIC: &[u8; 4] = b"KVSB";
+const VERSION: u32 = 1;
+
+/// Writes a self-contained backup bundle: a small header (magic,
+/// version, entry count) followed by length-prefixed key/value pairs.
+/// Unlike copying the raw `.log` segment files, a bundle is compacted
+/// and independent of the UUID segment names used internally, which is
+/// what makes it relocatable between stores and machines.
+pub fn write_bundle(path: &Path, entries: &[(String, String)]) -> Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(MAGIC)?;
+    file.write_all(&VERSION.to_ne_bytes())?;
+    file.write_all(&(entries.len() as u64).to_ne_bytes())?;
+    for (key, value) in entries {
+        write_len_prefixed(&mut file, key.as_bytes())?;
+        write_len_prefixed(&mut file, value.as_bytes())?;
+    }
+    Ok(())
+}
+
+fn write_len_prefixed(file: &mut File, bytes: &[u8]) -> Result<()> {
+    file.write_all(&bytes.len().to_ne_bytes())?;
+    file.write_all(bytes)?;
+    Ok(())
+}
+
+/// Streams a bundle's entries back out in order, checking the header on
+/// open so a corrupt or foreign file is rejected up front.
+pub struct BundleReader {
+    file: File,
+    pub count: u64,
+}
+
+impl BundleReader {
+    pub fn open(path: &Path) -> Result<BundleReader> {
+        let mut file = File::open(path)?;
+
+        let mut magic = [0u8; 4];
+        file.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(KvsError::BundleError(format!(
+                "{}: not a kvs bundle",
+                path.display()
+            )));
+        }
+
+        let mut version_buf = [0u8; 4];
+        file.read_exact(&mut version_buf)?;
+        let version = u32::from_ne_bytes(version_buf);
+        if version != VERSION {
+            return Err(KvsError::BundleError(format!(
+                "{}: unsupported bundle version {version}",
+                path.display()
+            )));
+        }
+
+        let mut count_buf = [0u8; 8];
+        file.read_exact(&mut count_buf)?;
+        let count = u64::from_ne_bytes(count_buf);
+
+        Ok(BundleReader { file, count })
+    }
+
+    pub fn read_entry(&mut self) -> Result<(String, String)> {
+        let key = self.read_len_prefixed()?;
+        let value = self.read_len_prefixed()?;
+        Ok((key, value))
+    }
+
+    fn read_len_prefixed(&mut self) -> Result<String> {
+        let mut len_buf = [0u8; std::mem::size_of::<usize>()];
+        self.file.read_exact(&mut len_buf)?;
+        let len = usize::from_ne_bytes(len_buf);
+
+        // A corrupt length prefix shouldn't make us try to allocate an
+        // unreasonable buffer; it can never legitimately exceed what's
+        // left in the file.
+        let remaining = self.file.metadata()?.len() - self.file.stream_position()?;
+        if len as u64 > remaining {
+            return Err(KvsError::BundleError(format!(
+                "corrupt bundle: record length {len} exceeds remaining file size {remaining}"
+            )));
+        }
+
+        let mut buf = vec![0u8; len];
+        self.file.read_exact(&mut buf)?;
+        Ok(String::from_utf8(buf)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_bundle_path() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("kvs-bundle-test-{}.bundle", uuid::Uuid::now_v7()))
+    }
+
+    #[test]
+    fn round_trips_entries_through_a_bundle_file() {
+        let path = temp_bundle_path();
+        let entries = vec![
+            ("a".to_string(), "1".to_string()),
+            ("b".to_string(), "2".to_string()),
+            ("c".to_string(), String::new()),
+        ];
+
+        write_bundle(&path, &entries).unwrap();
+
+        let mut reader = BundleReader::open(&path).unwrap();
+        assert_eq!(reader.count, entries.len() as u64);
+        let mut read_back = Vec::new();
+        for _ in 0..reader.count {
+            read_back.push(reader.read_entry().unwrap());
+        }
+        assert_eq!(read_back, entries);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_a_file_without_the_bundle_magic() {
+        let path = temp_bundle_path();
+        std::fs::write(&path, b"not a bundle").unwrap();
+
+        let result = BundleReader::open(&path);
+        assert!(matches!(result, Err(KvsError::BundleError(_))));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,208 @@
+use crate::error::{KvsError, Result};
+
+/// Compression codec applied to a single record's value before it is
+/// written to a log segment. The codec tag is stored alongside the
+/// record so `Wal::read_value` knows how to reverse it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    None,
+    Yaz0,
+}
+
+impl Codec {
+    pub fn to_byte(self) -> u8 {
+        match self {
+            Codec::None => 0,
+            Codec::Yaz0 => 1,
+        }
+    }
+
+    pub fn from_byte(byte: u8) -> Result<Codec> {
+        match byte {
+            0 => Ok(Codec::None),
+            1 => Ok(Codec::Yaz0),
+            other => Err(KvsError::UnknownCodec(other)),
+        }
+    }
+
+    /// Looks up a codec by the name used in `[core] codec = ...` config.
+    pub fn from_name(name: &str) -> Result<Codec> {
+        match name.to_ascii_lowercase().as_str() {
+            "none" => Ok(Codec::None),
+            "yaz0" => Ok(Codec::Yaz0),
+            other => Err(KvsError::UnknownCodecName(other.to_string())),
+        }
+    }
+
+    pub fn compress(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Codec::None => data.to_vec(),
+            Codec::Yaz0 => yaz0::compress(data),
+        }
+    }
+
+    pub fn decompress(self, data: &[u8], original_len: usize) -> Vec<u8> {
+        match self {
+            Codec::None => data.to_vec(),
+            Codec::Yaz0 => yaz0::decompress(data, original_len),
+        }
+    }
+}
+
+/// A small, dependency-free Yaz0-style LZ77 codec. The compressed stream
+/// is a series of groups, each starting with a 1-byte bitmask consumed
+/// MSB-first: a set bit copies the next literal byte straight to output,
+/// an unset bit reads a back-reference (a 12-bit distance plus a length
+/// encoded in either 2 or 3 bytes). Back-reference copies are done one
+/// byte at a time since the source and destination ranges may overlap.
+mod yaz0 {
+    const MIN_MATCH: usize = 3;
+    const MAX_SHORT_MATCH: usize = 2 + 0x0f;
+    const MAX_LONG_MATCH: usize = 0x12 + 0xff;
+    const MAX_DISTANCE: usize = 0x1000;
+
+    pub fn compress(input: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut i = 0;
+        while i < input.len() {
+            let mut flags = 0u8;
+            let mut group = Vec::new();
+            for bit in 0..8 {
+                if i >= input.len() {
+                    break;
+                }
+                match find_match(input, i) {
+                    Some((distance, length)) => {
+                        let d = distance - 1;
+                        if length <= MAX_SHORT_MATCH {
+                            let n = (length - 2) as u8;
+                            group.push((n << 4) | ((d >> 8) as u8 & 0x0f));
+                            group.push((d & 0xff) as u8);
+                        } else {
+                            group.push((d >> 8) as u8 & 0x0f);
+                            group.push((d & 0xff) as u8);
+                            group.push((length - 0x12) as u8);
+                        }
+                        i += length;
+                    }
+                    None => {
+                        flags |= 1 << (7 - bit);
+                        group.push(input[i]);
+                        i += 1;
+                    }
+                }
+            }
+            out.push(flags);
+            out.extend_from_slice(&group);
+        }
+        out
+    }
+
+    fn find_match(input: &[u8], pos: usize) -> Option<(usize, usize)> {
+        let max_distance = pos.min(MAX_DISTANCE);
+        let max_length = (input.len() - pos).min(MAX_LONG_MATCH);
+        if max_distance == 0 || max_length < MIN_MATCH {
+            return None;
+        }
+        let mut best_len = 0;
+        let mut best_distance = 0;
+        for distance in 1..=max_distance {
+            let start = pos - distance;
+            let mut len = 0;
+            while len < max_length && input[start + len] == input[pos + len] {
+                len += 1;
+            }
+            if len > best_len {
+                best_len = len;
+                best_distance = distance;
+            }
+        }
+        if best_len >= MIN_MATCH {
+            Some((best_distance, best_len))
+        } else {
+            None
+        }
+    }
+
+    pub fn decompress(input: &[u8], original_len: usize) -> Vec<u8> {
+        let mut out = Vec::with_capacity(original_len);
+        let mut idx = 0;
+        while out.len() < original_len {
+            let mask = input[idx];
+            idx += 1;
+            for bit in (0..8).rev() {
+                if out.len() >= original_len {
+                    break;
+                }
+                if mask & (1 << bit) != 0 {
+                    out.push(input[idx]);
+                    idx += 1;
+                } else {
+                    let b0 = input[idx];
+                    idx += 1;
+                    let n = (b0 >> 4) & 0x0f;
+                    let b1 = input[idx];
+                    idx += 1;
+                    let distance = (((b0 & 0x0f) as usize) << 8) | b1 as usize;
+                    let length = if n != 0 {
+                        n as usize + 2
+                    } else {
+                        let b2 = input[idx];
+                        idx += 1;
+                        b2 as usize + 0x12
+                    };
+                    let start = out.len() - distance - 1;
+                    for i in 0..length {
+                        let byte = out[start + i];
+                        out.push(byte);
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(data: &[u8]) {
+        let compressed = Codec::Yaz0.compress(data);
+        let restored = Codec::Yaz0.decompress(&compressed, data.len());
+        assert_eq!(restored, data);
+    }
+
+    #[test]
+    fn round_trips_empty_input() {
+        round_trip(b"");
+    }
+
+    #[test]
+    fn round_trips_input_with_no_matches() {
+        round_trip(b"abcdefgh");
+    }
+
+    #[test]
+    fn round_trips_a_short_back_reference() {
+        round_trip(b"abcabcabc");
+    }
+
+    /// A run long enough that the back-reference distance is shorter
+    /// than the match length, so decompression copies from output
+    /// bytes it *just* wrote in this same copy (the overlapping-copy
+    /// case `decompress` handles one byte at a time).
+    #[test]
+    fn round_trips_a_long_overlapping_run() {
+        round_trip(&[b'x'; 500]);
+    }
+
+    #[test]
+    fn round_trips_mixed_literals_and_matches() {
+        let mut data = Vec::new();
+        data.extend_from_slice(b"The quick brown fox jumps over the lazy dog. ");
+        data.extend_from_slice(&[b'z'; 300]);
+        data.extend_from_slice(b"The quick brown fox jumps over the lazy dog.");
+        round_trip(&data);
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+use crate::error::{KvsError, Result};
+
+type Sections = HashMap<String, HashMap<String, String>>;
+
+/// Runtime tunables read from `.log/config` at `KvStore::open` time, so
+/// operators can adjust segment size, compaction threshold, and the
+/// default compression codec per store without recompiling.
+#[derive(Debug, Default)]
+pub struct Config {
+    pub segment_size: Option<u64>,
+    pub compaction_threshold: Option<f32>,
+    pub default_codec: Option<String>,
+}
+
+impl Config {
+    /// Reads and parses `path`, returning defaults if it doesn't exist.
+    /// INI-style: `[section]` headers, `key = value` items, `#`/`;`
+    /// comments, `%include <path>` to splice in another file in place
+    /// (relative to the file doing the including), and `%unset <key>`
+    /// to drop a key set earlier so a base config can be overridden.
+    pub fn load(path: &Path) -> Result<Config> {
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+        let mut sections = Sections::new();
+        let mut including = HashSet::new();
+        load_into(path, &mut sections, &mut including)?;
+        let core = sections.get("core");
+        Ok(Config {
+            segment_size: core
+                .and_then(|s| s.get("segment_size"))
+                .map(|v| parse_u64(path, v))
+                .transpose()?,
+            compaction_threshold: core
+                .and_then(|s| s.get("compaction_threshold"))
+                .map(|v| parse_f32(path, v))
+                .transpose()?,
+            default_codec: core.and_then(|s| s.get("codec")).cloned(),
+        })
+    }
+}
+
+fn parse_u64(path: &Path, value: &str) -> Result<u64> {
+    value
+        .parse()
+        .map_err(|_| KvsError::ConfigError(format!("{}: invalid integer: {value}", path.display())))
+}
+
+fn parse_f32(path: &Path, value: &str) -> Result<f32> {
+    value
+        .parse()
+        .map_err(|_| KvsError::ConfigError(format!("{}: invalid float: {value}", path.display())))
+}
+
+/// Reads `path` into `sections`, recursing into `%include`d files.
+/// `including` holds the canonical path of every file currently on the
+/// include stack so a file that (directly or transitively) includes
+/// itself is rejected with a `ConfigError` instead of recursing until
+/// the stack overflows.
+fn load_into(path: &Path, sections: &mut Sections, including: &mut HashSet<PathBuf>) -> Result<()> {
+    let canonical = path
+        .canonicalize()
+        .map_err(|e| KvsError::ConfigError(format!("{}: {e}", path.display())))?;
+    if !including.insert(canonical.clone()) {
+        return Err(KvsError::ConfigError(format!(
+            "{}: %include cycle detected",
+            path.display()
+        )));
+    }
+    let result = parse_lines(path, sections, including);
+    including.remove(&canonical);
+    result
+}
+
+fn parse_lines(path: &Path, sections: &mut Sections, including: &mut HashSet<PathBuf>) -> Result<()> {
+    let contents = read_to_string(path)
+        .map_err(|e| KvsError::ConfigError(format!("{}: {e}", path.display())))?;
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut section = String::from("core");
+
+    for (lineno, raw_line) in contents.lines().enumerate() {
+        let line = raw_line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+
+        if let Some(rest) = line.strip_prefix("%include") {
+            let included = rest.trim();
+            if included.is_empty() {
+                return Err(malformed(path, lineno, "%include requires a path"));
+            }
+            load_into(&resolve(dir, included), sections, including)?;
+            continue;
+        }
+
+        if let Some(rest) = line.strip_prefix("%unset") {
+            let key = rest.trim();
+            if key.is_empty() {
+                return Err(malformed(path, lineno, "%unset requires a key"));
+            }
+            if let Some(values) = sections.get_mut(&section) {
+                values.remove(key);
+            }
+            continue;
+        }
+
+        if let Some(stripped) = line.strip_prefix('[') {
+            let name = stripped
+                .strip_suffix(']')
+                .ok_or_else(|| malformed(path, lineno, &format!("malformed section header: {line}")))?;
+            section = name.trim().to_string();
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| malformed(path, lineno, &format!("expected `key = value`, got: {line}")))?;
+        sections
+            .entry(section.clone())
+            .or_default()
+            .insert(key.trim().to_string(), value.trim().to_string());
+    }
+    Ok(())
+}
+
+fn malformed(path: &Path, lineno: usize, message: &str) -> KvsError {
+    KvsError::ConfigError(format!("{}:{}: {message}", path.display(), lineno + 1))
+}
+
+fn resolve(base_dir: &Path, included: &str) -> PathBuf {
+    let included_path = Path::new(included);
+    if included_path.is_absolute() {
+        included_path.to_path_buf()
+    } else {
+        base_dir.join(included_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{create_dir_all, remove_dir_all, write};
+
+    /// A config that `%include`s itself must be rejected with a
+    /// `ConfigError`, not overflow the stack through unbounded recursion.
+    #[test]
+    fn self_including_config_returns_error() {
+        let dir = std::env::temp_dir().join(format!("kvs-config-test-{}", uuid::Uuid::now_v7()));
+        create_dir_all(&dir).unwrap();
+        let config_path = dir.join("config");
+        write(&config_path, "%include config\n").unwrap();
+
+        let result = Config::load(&config_path);
+        assert!(matches!(result, Err(KvsError::ConfigError(_))));
+
+        remove_dir_all(&dir).unwrap();
+    }
+}
@@ -14,6 +14,21 @@ pub enum KvsError {
 
     #[error("Key {0} not found")]
     KeyNotFound(String),
+
+    #[error("Unknown codec byte: {0}")]
+    UnknownCodec(u8),
+
+    #[error("Unknown record kind byte: {0}")]
+    UnknownRecordKind(u8),
+
+    #[error("Unknown codec name: {0}")]
+    UnknownCodecName(String),
+
+    #[error("Config error: {0}")]
+    ConfigError(String),
+
+    #[error("Bundle error: {0}")]
+    BundleError(String),
 }
 
 pub type Result<T> = std::result::Result<T, KvsError>;
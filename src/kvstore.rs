@@ -1,54 +1,244 @@
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::path::Path;
 
+use crate::bundle::{self, BundleReader};
+use crate::codec::Codec;
+use crate::config::Config;
 use crate::error::{KvsError, Result};
-use crate::log::{Operation, ValueEntry, Wal};
+use crate::log::{Operation, RecordKind, ValueEntry, Wal};
+
+type ContentHash = [u8; 32];
+
+fn hash_value(value: &str) -> ContentHash {
+    let mut hasher = Sha256::new();
+    hasher.update(value.as_bytes());
+    hasher.finalize().into()
+}
+
+/// One physical value and how many live keys currently point at it.
+/// Tracked so `set` can dedup identical values and `compact` can tell
+/// whether a value is still referenced by anyone.
+struct PhysicalValue {
+    entry: ValueEntry,
+    refcount: u64,
+}
+
+/// A snapshot of how much space a store is using and how much of it is
+/// reclaimable, as returned by `KvStore::stats` and printed by `kvs stats`.
+#[derive(Debug)]
+pub struct Stats {
+    pub live_keys: usize,
+    pub segments: usize,
+    pub total_bytes: u64,
+    pub live_bytes: u64,
+    pub dead_byte_ratio: f32,
+}
 
 pub struct KvStore {
     map: HashMap<String, ValueEntry>,
     log: Wal,
-    ops_count: u64,
+    content_index: HashMap<ContentHash, PhysicalValue>,
+    compaction_threshold: f32,
+    default_codec: Codec,
+    /// Running totals kept in sync on every write/remove/compact, so
+    /// `compact_if_needed` can check the dead-byte ratio in O(1) instead
+    /// of re-statting every segment and re-pricing every live record on
+    /// every single `set`/`remove`.
+    total_bytes: u64,
+    live_bytes: u64,
 }
 
 impl KvStore {
-    const COMPACTION_THRESHOLD: f32 = 0.7;
+    /// Compaction runs once dead bytes reach this fraction of total bytes
+    /// on disk, so it's driven by actual wasted space rather than a key
+    /// count that ignores value sizes and tombstones. Overridable via
+    /// `[core] compaction_threshold` in `.log/config`.
+    const DEFAULT_COMPACTION_THRESHOLD: f32 = 0.3;
+    /// Values at or above this length are stored compressed; small values
+    /// aren't worth the codec overhead.
+    const COMPRESSION_THRESHOLD: usize = 256;
 
     pub fn open(path: &Path) -> Result<KvStore> {
+        let config = Config::load(&path.join(".log").join("config"))?;
+        let segment_size = config.segment_size.unwrap_or(Wal::DEFAULT_SEGMENT_SIZE);
+        let compaction_threshold = config
+            .compaction_threshold
+            .unwrap_or(KvStore::DEFAULT_COMPACTION_THRESHOLD);
+        let default_codec = match &config.default_codec {
+            Some(name) => Codec::from_name(name)?,
+            None => Codec::None,
+        };
+
         let mut kvstore = KvStore {
             map: HashMap::new(),
-            log: Wal::open(path)?,
-            ops_count: 0,
+            log: Wal::open(path, segment_size)?,
+            content_index: HashMap::new(),
+            compaction_threshold,
+            default_codec,
+            total_bytes: 0,
+            live_bytes: 0,
         };
-        kvstore.ops_count += kvstore.log.replay(&mut kvstore.map)?;
+        kvstore.log.replay(&mut kvstore.map)?;
+        kvstore.rebuild_content_index()?;
+        kvstore.total_bytes = kvstore.log.total_bytes()?;
+        kvstore.live_bytes = kvstore.live_bytes_total();
         Ok(kvstore)
     }
 
-    pub fn get(&mut self, key: String) -> Result<Option<String>> {
-        match self.map.get(&key) {
-            Some(ve) => {
-                if ve.vsz > 0 {
-                    Ok(Some(self.log.read_value(ve)?))
-                } else {
-                    Ok(None)
+    /// Live key count, segment/byte totals, and the resulting dead-byte
+    /// ratio, for `kvs stats` and for driving compaction. `total_bytes`
+    /// and `live_bytes` are running totals (see the fields on `KvStore`),
+    /// so this is O(1) rather than re-statting segments and re-pricing
+    /// every live record.
+    pub fn stats(&self) -> Result<Stats> {
+        Ok(Stats {
+            live_keys: self.map.len(),
+            segments: self.log.segment_count(),
+            total_bytes: self.total_bytes,
+            live_bytes: self.live_bytes,
+            dead_byte_ratio: Self::dead_byte_ratio(self.total_bytes, self.live_bytes),
+        })
+    }
+
+    fn dead_byte_ratio(total_bytes: u64, live_bytes: u64) -> f32 {
+        if total_bytes > 0 {
+            (total_bytes.saturating_sub(live_bytes)) as f32 / total_bytes as f32
+        } else {
+            0.0
+        }
+    }
+
+    /// Sums `record_footprint` over every currently live entry. O(live
+    /// keys); only meant to be called right after a full rescan (startup,
+    /// post-compaction), never per-op.
+    fn live_bytes_total(&self) -> u64 {
+        self.map
+            .iter()
+            .map(|(k, ve)| Self::record_footprint(k, ve) as u64)
+            .sum()
+    }
+
+    /// Approximate on-disk size of the record this key actually owns.
+    /// A `Direct` entry's record holds its (compressed) value inline; a
+    /// `Ref` entry's record is just a pointer at another key's value, so
+    /// pricing it as if it held the value too would undercount dead
+    /// bytes and trigger compaction far too eagerly whenever values are
+    /// shared (see content-addressed dedup).
+    fn record_footprint(key: &str, ve: &ValueEntry) -> usize {
+        let common = std::mem::size_of::<usize>() * 2 // ksz, vsz
+            + 1; // record kind
+        match ve.kind {
+            RecordKind::Ref => {
+                let pointer = std::mem::size_of::<usize>() // target file id length prefix
+                    + ve.file_id.len()
+                    + std::mem::size_of::<u64>() // target vpos
+                    + std::mem::size_of::<usize>() // target vsz
+                    + 1 // target codec
+                    + std::mem::size_of::<usize>(); // target orig_len
+                common + pointer + key.len()
+            }
+            RecordKind::Direct => {
+                let mut header = common + 1; // codec
+                if ve.codec != Codec::None {
+                    header += std::mem::size_of::<usize>(); // original length
                 }
+                header + key.len() + ve.vsz
             }
+            RecordKind::Tombstone => common + key.len(),
+        }
+    }
+
+    /// Recomputes the content hash → physical value table from the
+    /// current map. Each distinct (file, offset) is hashed only once,
+    /// since every key sharing that location shares its content.
+    fn rebuild_content_index(&mut self) -> Result<()> {
+        self.content_index.clear();
+        let mut location_hashes: HashMap<(String, u64), ContentHash> = HashMap::new();
+        for ve in self.map.values() {
+            let location = ((*ve.file_id).clone(), ve.vpos);
+            let hash = match location_hashes.get(&location) {
+                Some(hash) => *hash,
+                None => {
+                    let value = self.log.read_value(ve)?;
+                    let hash = hash_value(&value);
+                    location_hashes.insert(location, hash);
+                    hash
+                }
+            };
+            let physical = self
+                .content_index
+                .entry(hash)
+                .or_insert_with(|| PhysicalValue {
+                    entry: ve.clone(),
+                    refcount: 0,
+                });
+            physical.refcount += 1;
+        }
+        Ok(())
+    }
+
+    /// Finds the physical value `ve` belongs to by its storage location
+    /// and drops its refcount, since `ve`'s key no longer points at it.
+    fn release(&mut self, ve: &ValueEntry) {
+        if let Some(physical) = self
+            .content_index
+            .values_mut()
+            .find(|p| *p.entry.file_id == *ve.file_id && p.entry.vpos == ve.vpos)
+        {
+            physical.refcount = physical.refcount.saturating_sub(1);
+        }
+    }
+
+    pub fn get(&mut self, key: String) -> Result<Option<String>> {
+        match self.map.get(&key) {
+            Some(ve) => Ok(Some(self.log.read_value(ve)?)),
             None => Ok(None),
         }
     }
 
     pub fn set(&mut self, key: String, value: String) -> Result<()> {
-        let ve = self.log.write(&key, &value, Operation::SET)?;
+        let hash = hash_value(&value);
+        let existing = self.content_index.get(&hash).map(|p| p.entry.clone());
+        let ve = match existing {
+            Some(target) => self.log.write_ref(&key, &target)?,
+            None => {
+                let codec = if value.len() >= Self::COMPRESSION_THRESHOLD {
+                    self.default_codec
+                } else {
+                    Codec::None
+                };
+                self.log.write(&key, &value, Operation::SET, codec)?
+            }
+        };
+
+        self.total_bytes += Self::record_footprint(&key, &ve) as u64;
+        if let Some(old_ve) = self.map.get(&key).cloned() {
+            self.live_bytes -= Self::record_footprint(&key, &old_ve) as u64;
+            self.release(&old_ve);
+        }
+        self.content_index
+            .entry(hash)
+            .or_insert_with(|| PhysicalValue {
+                entry: ve.clone(),
+                refcount: 0,
+            })
+            .refcount += 1;
+        self.live_bytes += Self::record_footprint(&key, &ve) as u64;
+
         self.map.insert(key, ve);
         self.compact_if_needed()?;
-        self.ops_count += 1;
         Ok(())
     }
 
     pub fn remove(&mut self, key: String) -> Result<()> {
         let flag_value = String::from("");
-        if self.map.remove(&key).is_some() {
-            self.log.write(&key, &flag_value, Operation::RM)?;
-            self.ops_count += 1;
+        if let Some(ve) = self.map.remove(&key) {
+            self.live_bytes -= Self::record_footprint(&key, &ve) as u64;
+            self.release(&ve);
+            let tombstone = self.log.write(&key, &flag_value, Operation::RM, Codec::None)?;
+            self.total_bytes += Self::record_footprint(&key, &tombstone) as u64;
+            self.compact_if_needed()?;
             Ok(())
         } else {
             Err(KvsError::KeyNotFound(key))
@@ -56,9 +246,155 @@ impl KvStore {
     }
 
     fn compact_if_needed(&mut self) -> Result<()> {
-        if self.map.len() as f32 / self.ops_count as f32 <= KvStore::COMPACTION_THRESHOLD {
+        if self.total_bytes > 0
+            && Self::dead_byte_ratio(self.total_bytes, self.live_bytes) >= self.compaction_threshold
+        {
             self.log.compact(&mut self.map)?;
+            self.rebuild_content_index()?;
+            self.total_bytes = self.log.total_bytes()?;
+            self.live_bytes = self.live_bytes_total();
+        }
+        Ok(())
+    }
+
+    /// Writes every live key/value pair into a single portable bundle
+    /// file at `path`, suitable for moving this store between machines
+    /// or taking a consistent backup independent of segment layout.
+    pub fn export(&mut self, path: &Path) -> Result<()> {
+        let keys: Vec<String> = self.map.keys().cloned().collect();
+        let mut entries = Vec::with_capacity(keys.len());
+        for key in keys {
+            let value = self.log.read_value(self.map.get(&key).unwrap())?;
+            entries.push((key, value));
+        }
+        bundle::write_bundle(path, &entries)
+    }
+
+    /// Restores the key/value pairs in the bundle at `path` into this
+    /// store, going through the normal `set` path so compression,
+    /// dedup, and compaction bookkeeping stay consistent.
+    pub fn import(&mut self, path: &Path) -> Result<()> {
+        let mut reader = BundleReader::open(path)?;
+        for _ in 0..reader.count {
+            let (key, value) = reader.read_entry()?;
+            self.set(key, value)?;
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::remove_dir_all;
+
+    fn temp_store_dir() -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("kvs-test-{}", uuid::Uuid::now_v7()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Two keys sharing a value should dedup to a single physical value,
+    /// so repeatedly setting that value never manufactures dead bytes
+    /// and never triggers compaction (see content-addressed dedup).
+    #[test]
+    fn dedup_does_not_trigger_spurious_compaction() {
+        let dir = temp_store_dir();
+        let mut store = KvStore::open(&dir).unwrap();
+
+        store.set("dup".into(), "samevalue12345".into()).unwrap();
+        store.set("dup2".into(), "samevalue12345".into()).unwrap();
+        let stats = store.stats().unwrap();
+        assert_eq!(stats.dead_byte_ratio, 0.0);
+
+        // Sharing a value across more keys never produces dead bytes on
+        // its own, so it should never cross the compaction threshold
+        // and force a compaction.
+        for i in 0..5 {
+            store
+                .set(format!("dup_extra{i}"), "samevalue12345".into())
+                .unwrap();
+            let stats = store.stats().unwrap();
+            assert_eq!(stats.dead_byte_ratio, 0.0);
+        }
+
+        remove_dir_all(&dir).unwrap();
+    }
+
+    /// The running `total_bytes`/`live_bytes` counters maintained by
+    /// `set`/`remove`/`compact_if_needed` must always agree with a full
+    /// rescan (what `KvStore::open` computes from scratch on replay), or
+    /// the O(1) dead-byte-ratio check drifts from reality over time.
+    #[test]
+    fn incremental_stats_match_a_fresh_rescan() {
+        let dir = temp_store_dir();
+        let mut store = KvStore::open(&dir).unwrap();
+
+        for i in 0..8 {
+            store.set(format!("k{i}"), format!("value-{i}")).unwrap();
+        }
+        store.set("k0".into(), "overwritten".into()).unwrap();
+        store.remove("k1".into()).unwrap();
+        store.remove("k2".into()).unwrap();
+
+        let live_stats = store.stats().unwrap();
+        let reopened = KvStore::open(&dir).unwrap();
+        let rescanned_stats = reopened.stats().unwrap();
+
+        assert_eq!(live_stats.total_bytes, rescanned_stats.total_bytes);
+        assert_eq!(live_stats.live_bytes, rescanned_stats.live_bytes);
+
+        remove_dir_all(&dir).unwrap();
+    }
+
+    /// A value at or above `COMPRESSION_THRESHOLD`, with a configured
+    /// default codec, should round-trip through `set`/`get` compressed
+    /// and decompressed correctly end to end.
+    #[test]
+    fn large_value_round_trips_through_configured_codec() {
+        let dir = temp_store_dir();
+        std::fs::create_dir_all(dir.join(".log")).unwrap();
+        std::fs::write(dir.join(".log").join("config"), "[core]\ncodec = yaz0\n").unwrap();
+        let mut store = KvStore::open(&dir).unwrap();
+
+        let value: String = "the quick brown fox jumps over the lazy dog "
+            .repeat(10)
+            .chars()
+            .take(300)
+            .collect();
+        assert!(value.len() >= KvStore::COMPRESSION_THRESHOLD);
+        store.set("big".into(), value.clone()).unwrap();
+
+        assert_eq!(store.get("big".into()).unwrap(), Some(value));
+
+        remove_dir_all(&dir).unwrap();
+    }
+
+    /// Exporting a store's keys and importing the resulting bundle into
+    /// a fresh store must reproduce every key/value pair.
+    #[test]
+    fn export_then_import_round_trips_into_a_fresh_store() {
+        let src_dir = temp_store_dir();
+        let mut src = KvStore::open(&src_dir).unwrap();
+        src.set("foo".into(), "bar".into()).unwrap();
+        src.set("baz".into(), "qux".into()).unwrap();
+        src.set("shared1".into(), "samevalue".into()).unwrap();
+        src.set("shared2".into(), "samevalue".into()).unwrap();
+
+        let bundle_path = src_dir.join("backup.bundle");
+        src.export(&bundle_path).unwrap();
+
+        let dst_dir = temp_store_dir();
+        let mut dst = KvStore::open(&dst_dir).unwrap();
+        dst.import(&bundle_path).unwrap();
+
+        assert_eq!(dst.get("foo".into()).unwrap(), Some("bar".into()));
+        assert_eq!(dst.get("baz".into()).unwrap(), Some("qux".into()));
+        assert_eq!(dst.get("shared1".into()).unwrap(), Some("samevalue".into()));
+        assert_eq!(dst.get("shared2".into()).unwrap(), Some("samevalue".into()));
+        assert_eq!(dst.stats().unwrap().live_keys, src.stats().unwrap().live_keys);
+
+        remove_dir_all(&src_dir).unwrap();
+        remove_dir_all(&dst_dir).unwrap();
+    }
+}
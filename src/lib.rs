@@ -1,3 +1,6 @@
+pub mod bundle;
+pub mod codec;
+pub mod config;
 pub mod error;
 pub mod kvstore;
 pub mod log;
@@ -7,6 +7,7 @@ use std::{
 };
 use uuid::Uuid;
 
+use crate::codec::Codec;
 use crate::error::{KvsError, Result};
 
 #[derive(Debug)]
@@ -15,25 +16,98 @@ pub enum Operation {
     RM,
 }
 
+#[derive(Debug, Clone)]
 pub struct ValueEntry {
     pub file_id: Box<String>,
     pub vsz: usize,
     pub vpos: u64,
+    pub codec: Codec,
+    pub orig_len: usize,
+    /// Whether *this key's own* record is stored inline (`Direct`) or as
+    /// a pointer at a value owned by another key (`Ref`). Two keys can
+    /// share a physical location while disagreeing on this field, since
+    /// only one of them is the `Direct` owner.
+    pub kind: RecordKind,
+}
+
+/// What a record's on-disk header says about how to interpret it:
+/// a live value stored inline, a tombstone, or a pointer at a value
+/// already stored elsewhere (used for content-addressed dedup).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordKind {
+    Direct,
+    Tombstone,
+    Ref,
+}
+
+impl RecordKind {
+    fn to_byte(self) -> u8 {
+        match self {
+            RecordKind::Direct => 0,
+            RecordKind::Tombstone => 1,
+            RecordKind::Ref => 2,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Result<RecordKind> {
+        match byte {
+            0 => Ok(RecordKind::Direct),
+            1 => Ok(RecordKind::Tombstone),
+            2 => Ok(RecordKind::Ref),
+            other => Err(KvsError::UnknownRecordKind(other)),
+        }
+    }
+}
+
+enum RawRecord {
+    Direct {
+        key: String,
+        vsz: usize,
+        codec: Codec,
+        orig_len: usize,
+    },
+    Tombstone {
+        key: String,
+    },
+    Ref {
+        key: String,
+        target: ValueEntry,
+    },
 }
 
 pub struct Wal {
     dir: PathBuf,
     files: Vec<Box<String>>,
+    segment_size: u64,
 }
 
 impl Wal {
-    const SEGMENT_SIZE: u64 = 128;
+    pub const DEFAULT_SEGMENT_SIZE: u64 = 128;
 
-    pub fn open(path: &Path) -> Result<Wal> {
+    pub fn open(path: &Path, segment_size: u64) -> Result<Wal> {
         let dir = path.join(".log");
         create_dir_all(&dir)?;
         let files = Wal::search_log_files(&dir)?;
-        Ok(Wal { dir, files })
+        Ok(Wal {
+            dir,
+            files,
+            segment_size,
+        })
+    }
+
+    /// Number of log segment files currently making up this WAL.
+    pub fn segment_count(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Total size in bytes of every segment file on disk.
+    pub fn total_bytes(&self) -> Result<u64> {
+        let mut total = 0u64;
+        for f in &self.files {
+            let file = self.open_log_file(f)?;
+            total += file.metadata()?.len();
+        }
+        Ok(total)
     }
 
     pub fn search_log_files(path: &Path) -> Result<Vec<Box<String>>> {
@@ -79,12 +153,26 @@ impl Wal {
         Ok(total_entries)
     }
 
+    /// Rewrites every live entry into a fresh segment, dropping dead
+    /// records. Keys that share a physical value (see content-addressed
+    /// dedup in `KvStore`) are relocated together: the value is rewritten
+    /// once and every other key sharing it gets a `Ref` pointing at the
+    /// new location, so compaction never duplicates a shared value.
     pub fn compact(&mut self, map: &mut HashMap<String, ValueEntry>) -> Result<()> {
         let before = self.files.len();
         self.files.push(Box::new(Wal::generate_log_file_name()));
-        for (k, ve) in map {
-            let value = self.read_value(ve)?;
-            let nve = self.write(k, &value, Operation::SET)?;
+        let mut relocated: HashMap<(String, u64), ValueEntry> = HashMap::new();
+        for (k, ve) in map.iter_mut() {
+            let old_location = ((*ve.file_id).clone(), ve.vpos);
+            let nve = match relocated.get(&old_location) {
+                Some(target) => self.write_ref(k, target)?,
+                None => {
+                    let value = self.read_value(ve)?;
+                    let nve = self.write(k, &value, Operation::SET, ve.codec)?;
+                    relocated.insert(old_location, nve.clone());
+                    nve
+                }
+            };
             *ve = nve;
         }
         for _ in 0..before {
@@ -104,20 +192,32 @@ impl Wal {
         loop {
             let result = self.read(&mut offset, file_ref);
             match result {
-                Ok((key, size)) => {
-                    if size == 0 {
-                        map.remove(&key);
-                    } else {
-                        map.insert(
-                            key,
-                            ValueEntry {
-                                file_id: file_id.clone(),
-                                vsz: size,
-                                vpos: offset,
-                            },
-                        );
-                        offset += size as u64; // skip reading value
-                    }
+                Ok(RawRecord::Tombstone { key }) => {
+                    map.remove(&key);
+                    entry_count += 1;
+                }
+                Ok(RawRecord::Direct {
+                    key,
+                    vsz,
+                    codec,
+                    orig_len,
+                }) => {
+                    map.insert(
+                        key,
+                        ValueEntry {
+                            file_id: file_id.clone(),
+                            vsz,
+                            vpos: offset,
+                            codec,
+                            orig_len,
+                            kind: RecordKind::Direct,
+                        },
+                    );
+                    offset += vsz as u64; // skip reading value
+                    entry_count += 1;
+                }
+                Ok(RawRecord::Ref { key, target }) => {
+                    map.insert(key, target);
                     entry_count += 1;
                 }
                 Err(err) => match &err {
@@ -138,7 +238,7 @@ impl Wal {
         match self.files.last() {
             Some(f) => {
                 let current = self.open_log_file(f)?;
-                if current.metadata()?.len() >= Wal::SEGMENT_SIZE {
+                if current.metadata()?.len() >= self.segment_size {
                     self.files.push(Box::new(Wal::generate_log_file_name()));
                 }
             }
@@ -149,45 +249,133 @@ impl Wal {
         Ok(())
     }
 
-    pub fn write(&mut self, k: &String, v: &String, mode: Operation) -> Result<ValueEntry> {
+    pub fn write(&mut self, k: &String, v: &String, mode: Operation, codec: Codec) -> Result<ValueEntry> {
         self.create_log_file_if_needed()?;
         let mut current = self.open_log_file(self.files.last().unwrap())?;
 
         let ksz = (*k).len();
-        let vsz = match mode {
-            Operation::SET => (*v).len(),
-            Operation::RM => 0,
+        let kind = match mode {
+            Operation::SET => RecordKind::Direct,
+            Operation::RM => RecordKind::Tombstone,
+        };
+        let (vsz, orig_len, codec, payload) = match mode {
+            Operation::SET => {
+                let compressed = codec.compress((*v).as_bytes());
+                (compressed.len(), (*v).len(), codec, compressed)
+            }
+            Operation::RM => (0, 0, Codec::None, Vec::new()),
         };
 
-        let ksz_buf = ksz.to_ne_bytes();
-        current.write_all(&ksz_buf)?;
-        let vsz_buf = vsz.to_ne_bytes();
-        current.write_all(&vsz_buf)?;
+        current.write_all(&ksz.to_ne_bytes())?;
+        current.write_all(&vsz.to_ne_bytes())?;
+        current.write_all(&[kind.to_byte()])?;
+        if kind == RecordKind::Direct {
+            current.write_all(&[codec.to_byte()])?;
+            if codec != Codec::None {
+                current.write_all(&orig_len.to_ne_bytes())?;
+            }
+        }
 
         let key_buf = (*k).as_bytes();
         current.write_all(key_buf)?;
         let vpos = current.metadata()?.len();
         if vsz > 0 {
-            let val_buf = (*v).as_bytes();
-            current.write_all(val_buf)?;
+            current.write_all(&payload)?;
         }
         Ok(ValueEntry {
             file_id: self.files.last().unwrap().clone(),
             vsz,
             vpos,
+            codec,
+            orig_len,
+            kind,
+        })
+    }
+
+    /// Writes a pointer record for `k` at the existing physical value
+    /// `target`, without duplicating the value bytes on disk. Used by
+    /// content-addressed dedup in `KvStore` and by `compact` when it
+    /// relocates a value shared by several keys.
+    pub fn write_ref(&mut self, k: &String, target: &ValueEntry) -> Result<ValueEntry> {
+        self.create_log_file_if_needed()?;
+        let mut current = self.open_log_file(self.files.last().unwrap())?;
+
+        let ksz = (*k).len();
+        current.write_all(&ksz.to_ne_bytes())?;
+        current.write_all(&0usize.to_ne_bytes())?;
+        current.write_all(&[RecordKind::Ref.to_byte()])?;
+
+        let target_file_id = target.file_id.as_bytes();
+        current.write_all(&target_file_id.len().to_ne_bytes())?;
+        current.write_all(target_file_id)?;
+        current.write_all(&target.vpos.to_ne_bytes())?;
+        current.write_all(&target.vsz.to_ne_bytes())?;
+        current.write_all(&[target.codec.to_byte()])?;
+        current.write_all(&target.orig_len.to_ne_bytes())?;
+
+        current.write_all((*k).as_bytes())?;
+        Ok(ValueEntry {
+            file_id: target.file_id.clone(),
+            vsz: target.vsz,
+            vpos: target.vpos,
+            codec: target.codec,
+            orig_len: target.orig_len,
+            kind: RecordKind::Ref,
         })
     }
 
-    fn read(&self, offset: &mut u64, file_id: &String) -> Result<(String, usize)> {
+    fn read(&self, offset: &mut u64, file_id: &String) -> Result<RawRecord> {
         let ksz = self.read_size(offset, file_id)?;
         let vsz = self.read_size(offset, file_id)?;
+        let kind = self.read_kind(offset, file_id)?;
+        match kind {
+            RecordKind::Tombstone => {
+                let key = self.read_key(offset, ksz, file_id)?;
+                Ok(RawRecord::Tombstone { key })
+            }
+            RecordKind::Direct => {
+                let codec = self.read_codec(offset, file_id)?;
+                let orig_len = if codec == Codec::None {
+                    vsz
+                } else {
+                    self.read_size(offset, file_id)?
+                };
+                let key = self.read_key(offset, ksz, file_id)?;
+                Ok(RawRecord::Direct {
+                    key,
+                    vsz,
+                    codec,
+                    orig_len,
+                })
+            }
+            RecordKind::Ref => {
+                let target_file_id = self.read_string(offset, file_id)?;
+                let target_vpos = self.read_u64(offset, file_id)?;
+                let target_vsz = self.read_size(offset, file_id)?;
+                let target_codec = self.read_codec(offset, file_id)?;
+                let target_orig_len = self.read_size(offset, file_id)?;
+                let key = self.read_key(offset, ksz, file_id)?;
+                Ok(RawRecord::Ref {
+                    key,
+                    target: ValueEntry {
+                        file_id: Box::new(target_file_id),
+                        vsz: target_vsz,
+                        vpos: target_vpos,
+                        codec: target_codec,
+                        orig_len: target_orig_len,
+                        kind: RecordKind::Ref,
+                    },
+                })
+            }
+        }
+    }
 
+    fn read_key(&self, offset: &mut u64, ksz: usize, file_id: &String) -> Result<String> {
         let mut key_buf = vec![0u8; ksz];
         let current = self.open_log_file(file_id)?;
         current.read_exact_at(&mut key_buf, *offset)?;
         *offset += ksz as u64;
-        let key = String::from_utf8(key_buf)?;
-        Ok((key, vsz))
+        Ok(String::from_utf8(key_buf)?)
     }
 
     fn read_size(&self, offset: &mut u64, file_id: &String) -> Result<usize> {
@@ -199,10 +387,40 @@ impl Wal {
         Ok(size)
     }
 
+    fn read_u64(&self, offset: &mut u64, file_id: &String) -> Result<u64> {
+        let mut buf = [0u8; std::mem::size_of::<u64>()];
+        let current = self.open_log_file(file_id)?;
+        current.read_exact_at(&mut buf, *offset)?;
+        *offset += std::mem::size_of::<u64>() as u64;
+        Ok(u64::from_ne_bytes(buf))
+    }
+
+    fn read_string(&self, offset: &mut u64, file_id: &String) -> Result<String> {
+        let len = self.read_size(offset, file_id)?;
+        self.read_key(offset, len, file_id)
+    }
+
+    fn read_codec(&self, offset: &mut u64, file_id: &String) -> Result<Codec> {
+        let mut buf = [0u8; 1];
+        let current = self.open_log_file(file_id)?;
+        current.read_exact_at(&mut buf, *offset)?;
+        *offset += 1;
+        Codec::from_byte(buf[0])
+    }
+
+    fn read_kind(&self, offset: &mut u64, file_id: &String) -> Result<RecordKind> {
+        let mut buf = [0u8; 1];
+        let current = self.open_log_file(file_id)?;
+        current.read_exact_at(&mut buf, *offset)?;
+        *offset += 1;
+        RecordKind::from_byte(buf[0])
+    }
+
     pub fn read_value(&self, ve: &ValueEntry) -> Result<String> {
         let mut buf = vec![0u8; ve.vsz];
         let file = Wal::open_file(self.dir.join(Path::new(&*ve.file_id)))?;
         file.read_exact_at(&mut buf, ve.vpos)?;
-        Ok(String::from_utf8(buf)?)
+        let raw = ve.codec.decompress(&buf, ve.orig_len);
+        Ok(String::from_utf8(raw)?)
     }
 }